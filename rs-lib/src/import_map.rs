@@ -0,0 +1,115 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use anyhow::Result;
+use deno_ast::ModuleSpecifier;
+use serde::Deserialize;
+
+use crate::MappedSpecifier;
+
+#[derive(Deserialize)]
+struct ImportMapJson {
+  #[serde(default)]
+  imports: HashMap<String, String>,
+}
+
+/// A parsed Deno/WHATWG import map. Every top-level `imports` entry is
+/// kept as written, including `/`-suffixed prefix keys and
+/// bare-specifier keys (ex. `"preact": "npm:preact@10"`); those two
+/// kinds are skipped when the map is applied to a transform, which
+/// only uses exact, literal URL keys. The `scopes` object is not
+/// parsed.
+#[derive(Debug, Clone, Default)]
+pub struct ImportMap {
+  imports: Vec<(String, String)>,
+}
+
+impl ImportMap {
+  pub fn from_json(text: &str) -> Result<Self> {
+    let raw: ImportMapJson = serde_json::from_str(text)
+      .context("Failed deserializing import map")?;
+    Ok(ImportMap {
+      imports: raw.imports.into_iter().collect(),
+    })
+  }
+
+  /// Iterates over the top-level `imports` entries.
+  pub fn top_level_entries(&self) -> impl Iterator<Item = &(String, String)> {
+    self.imports.iter()
+  }
+}
+
+/// What a resolved import map target should feed into when wiring it
+/// up to the rest of the transform (see `specifier_mappings` and
+/// `redirects` on `TransformOptions`).
+pub enum ResolvedImportMapTarget {
+  Mapped(MappedSpecifier),
+  Redirect(ModuleSpecifier),
+}
+
+pub fn classify_resolved_target(
+  target: &str,
+) -> Result<ResolvedImportMapTarget> {
+  if let Some(bare) = target.strip_prefix("npm:") {
+    Ok(ResolvedImportMapTarget::Mapped(parse_npm_mapped_specifier(
+      bare,
+    )))
+  } else if !target.contains("://") {
+    // not a url and not an npm: specifier, so treat it as a bare
+    // node module name (ex. "path")
+    Ok(ResolvedImportMapTarget::Mapped(parse_npm_mapped_specifier(
+      target,
+    )))
+  } else {
+    let specifier = ModuleSpecifier::parse(target)
+      .with_context(|| format!("Invalid import map target: {}", target))?;
+    Ok(ResolvedImportMapTarget::Redirect(specifier))
+  }
+}
+
+fn parse_npm_mapped_specifier(text: &str) -> MappedSpecifier {
+  match text.rsplit_once('@') {
+    Some((name, version)) if !name.is_empty() => MappedSpecifier {
+      name: name.to_string(),
+      version: Some(version.to_string()),
+    },
+    _ => MappedSpecifier {
+      name: text.to_string(),
+      version: None,
+    },
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn parses_top_level_imports_entries() {
+    let map = ImportMap::from_json(
+      r#"{ "imports": { "preact": "npm:preact@10" } }"#,
+    )
+    .unwrap();
+    let entries = map.top_level_entries().collect::<Vec<_>>();
+    assert_eq!(
+      entries,
+      vec![&("preact".to_string(), "npm:preact@10".to_string())]
+    );
+  }
+
+  #[test]
+  fn ignores_scopes() {
+    // `scopes` isn't part of `ImportMapJson`, so it's simply absent
+    // from the parsed result rather than erroring
+    let map = ImportMap::from_json(
+      r#"{
+        "imports": { "preact": "npm:preact@10" },
+        "scopes": { "https://example.com/": { "preact": "npm:preact@8" } }
+      }"#,
+    )
+    .unwrap();
+    assert_eq!(map.top_level_entries().count(), 1);
+  }
+}
@@ -8,6 +8,7 @@ use std::path::PathBuf;
 
 use anyhow::Context;
 use anyhow::Result;
+use rayon::prelude::*;
 #[macro_use]
 extern crate lazy_static;
 
@@ -32,21 +33,27 @@ use visitors::GetGlobalTextChangesParams;
 use visitors::GetImportExportsTextChangesParams;
 
 pub use deno_ast::ModuleSpecifier;
+pub use import_map::ImportMap;
 pub use loader::LoadResponse;
 pub use loader::Loader;
 pub use utils::url_to_file_path;
 
 use crate::declaration_file_resolution::TypesDependency;
+use crate::import_map::classify_resolved_target;
+use crate::import_map::ResolvedImportMapTarget;
+use crate::type_reference_mappings::rewrite_type_references;
 use crate::utils::strip_bom;
 
 mod declaration_file_resolution;
 mod graph;
+mod import_map;
 mod loader;
 mod mappings;
 mod parser;
 mod polyfills;
 mod specifiers;
 mod text_changes;
+mod type_reference_mappings;
 mod utils;
 mod visitors;
 
@@ -115,6 +122,25 @@ pub struct TransformOptions {
   pub specifier_mappings: HashMap<ModuleSpecifier, MappedSpecifier>,
   /// Redirects one specifier to another specifier.
   pub redirects: HashMap<ModuleSpecifier, ModuleSpecifier>,
+  /// A Deno import map's top-level `imports` (from a `deno.json` or
+  /// `import_map.json`), pre-seeded into `specifier_mappings` and
+  /// `redirects`. Only exact, literal keys are applied — a `/`-suffixed
+  /// prefix key, a bare-specifier key (ex. `"preact": "npm:preact@10"`),
+  /// or any `scopes` entry would need to be resolved against each
+  /// specifier's referrer while building the module graph, which this
+  /// checkout's graph builder doesn't do, so those are not supported.
+  pub import_map: Option<ImportMap>,
+  /// Maps the literal specifier text of a `/// <reference types="..." />`
+  /// or `@deno-types="..."` directive (ex. `./node.d.ts`) to a published
+  /// `@types/*` package: the matching directive is stripped from the
+  /// emitted file text and the mapped package is added as a dependency
+  /// instead. This only rewrites the emitted text — it does not keep
+  /// the original target from being loaded and tracked as a
+  /// declaration-file dependency while building the module graph (that
+  /// would need the graph builder to honor this mapping too, which
+  /// this checkout's doesn't), so it can still surface alongside a
+  /// spurious declaration warning for the now-unused original target.
+  pub type_reference_mappings: HashMap<String, MappedSpecifier>,
 }
 
 struct EnvironmentContext<'a> {
@@ -131,12 +157,22 @@ pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
     anyhow::bail!("at least one entry point must be specified");
   }
 
+  let mut specifier_mappings = options.specifier_mappings;
+  let mut redirects = options.redirects;
+  if let Some(import_map) = &options.import_map {
+    apply_top_level_import_map_entries(
+      import_map,
+      &mut specifier_mappings,
+      &mut redirects,
+    )?;
+  }
+
   let (module_graph, specifiers) =
     crate::graph::ModuleGraph::build_with_specifiers(ModuleGraphOptions {
       entry_points: options.entry_points.clone(),
       test_entry_points: options.test_entry_points.clone(),
-      specifier_mappings: &options.specifier_mappings,
-      redirects: &options.redirects,
+      specifier_mappings: &specifier_mappings,
+      redirects: &redirects,
       loader: options.loader,
     })
     .await?;
@@ -150,7 +186,6 @@ pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
     .map(|m| (m.0.clone(), m.1.name.clone()))
     .collect();
 
-  // todo: parallelize
   let mut warnings = get_declaration_warnings(&specifiers);
   let mut main_env_context = EnvironmentContext {
     environment: TransformOutputEnvironment {
@@ -195,100 +230,76 @@ pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
     used_shim: false,
   };
 
-  for specifier in specifiers
+  let computations = specifiers
     .local
     .iter()
     .chain(specifiers.remote.iter())
     .chain(specifiers.types.iter().map(|(_, d)| &d.selected.specifier))
-  {
-    let module = module_graph.get(specifier);
-    let env_context = if specifiers.test_modules.contains(specifier) {
+    .collect::<Vec<_>>()
+    .into_par_iter()
+    .map(|specifier| {
+      let is_test = specifiers.test_modules.contains(specifier);
+      let (shim_file_specifier, shim_global_names) = if is_test {
+        (
+          test_env_context.shim_file_specifier,
+          &test_env_context.shim_global_names,
+        )
+      } else {
+        (
+          main_env_context.shim_file_specifier,
+          &main_env_context.shim_global_names,
+        )
+      };
+      compute_module_changes(ComputeModuleChangesParams {
+        specifier,
+        is_test,
+        module_graph: &module_graph,
+        mappings: &mappings,
+        all_specifier_mappings: &all_specifier_mappings,
+        shim_file_specifier,
+        shim_global_names,
+        type_reference_mappings: &options.type_reference_mappings,
+      })
+    })
+    .collect::<Result<Vec<_>>>()?;
+
+  // fold the per-module results into the two environments in a
+  // deterministic order (`computations` preserves the original,
+  // non-parallel iteration order regardless of completion order)
+  for computation in computations {
+    let env_context = if computation.is_test {
       &mut test_env_context
     } else {
       &mut main_env_context
     };
 
-    let file_text = match module {
-      ModuleRef::Es(module) => {
-        let parsed_source = module.parsed_source.clone();
-
-        let text_changes = parsed_source
-          .with_view(|program| -> Result<Vec<TextChange>> {
-            let ignore_line_indexes =
-              get_ignore_line_indexes(parsed_source.specifier(), &program);
-            warnings.extend(ignore_line_indexes.warnings);
-
-            fill_polyfills(&mut FillPolyfillsParams {
-              polyfills: &mut env_context.polyfills,
-              program: &program,
-              top_level_context: parsed_source.top_level_context(),
-            });
-
-            let mut text_changes = Vec::new();
-
-            // shim changes
-            {
-              let shim_relative_specifier = get_relative_specifier(
-                mappings.get_file_path(specifier),
-                mappings.get_file_path(env_context.shim_file_specifier),
-              );
-              let result =
-                get_global_text_changes(&GetGlobalTextChangesParams {
-                  program: &program,
-                  top_level_context: parsed_source.top_level_context(),
-                  shim_specifier: &shim_relative_specifier,
-                  shim_global_names: &env_context.shim_global_names,
-                  ignore_line_indexes: &ignore_line_indexes.line_indexes,
-                });
-              text_changes.extend(result.text_changes);
-              if result.imported_shim {
-                env_context.used_shim = true;
-              }
-            }
-
-            text_changes
-              .extend(get_deno_comment_directive_text_changes(&program));
-            text_changes.extend(get_import_exports_text_changes(
-              &GetImportExportsTextChangesParams {
-                specifier,
-                module_graph: &module_graph,
-                mappings: &mappings,
-                program: &program,
-                specifier_mappings: &all_specifier_mappings,
-              },
-            )?);
-
-            Ok(text_changes)
-          })
-          .with_context(|| {
-            format!(
-              "Issue getting text changes from {}",
-              parsed_source.specifier()
-            )
-          })?;
-
-        apply_text_changes(
-          parsed_source.source().text().to_string(),
-          text_changes,
-        )
-      }
-      ModuleRef::Synthetic(module) => {
-        if let Some(source) = &module.maybe_source {
-          format!(
-            "export default JSON.parse(`{}`);",
-            strip_bom(&source.replace("`", "\\`").replace("${", "\\${"))
-          )
-        } else {
-          continue;
+    warnings.extend(computation.warnings);
+    env_context.polyfills.extend(computation.polyfills);
+    if computation.used_shim {
+      env_context.used_shim = true;
+    }
+    for mapped in computation.extra_dependencies {
+      if let Some(version) = mapped.version {
+        if !env_context
+          .environment
+          .dependencies
+          .iter()
+          .any(|d| d.name == mapped.name)
+        {
+          env_context
+            .environment
+            .dependencies
+            .push(Dependency { name: mapped.name, version });
         }
       }
-    };
+    }
 
-    let file_path = mappings.get_file_path(specifier).to_owned();
-    env_context.environment.files.push(OutputFile {
-      file_path,
-      file_text,
-    });
+    if let Some(file_text) = computation.file_text {
+      env_context.environment.files.push(OutputFile {
+        file_path: mappings.get_file_path(&computation.specifier).to_owned(),
+        file_text,
+      });
+    }
   }
 
   check_add_polyfill_file_to_environment(
@@ -323,6 +334,135 @@ pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
   })
 }
 
+struct ComputeModuleChangesParams<'a> {
+  specifier: &'a ModuleSpecifier,
+  is_test: bool,
+  module_graph: &'a crate::graph::ModuleGraph,
+  mappings: &'a Mappings,
+  all_specifier_mappings: &'a HashMap<ModuleSpecifier, String>,
+  shim_file_specifier: &'a ModuleSpecifier,
+  shim_global_names: &'a HashSet<&'a str>,
+  type_reference_mappings: &'a HashMap<String, MappedSpecifier>,
+}
+
+struct ModuleChanges {
+  specifier: ModuleSpecifier,
+  is_test: bool,
+  file_text: Option<String>,
+  polyfills: HashSet<Polyfill>,
+  used_shim: bool,
+  warnings: Vec<String>,
+  extra_dependencies: Vec<MappedSpecifier>,
+}
+
+/// Computes a single module's output text, discovered polyfills, shim
+/// usage, and warnings. Depends only on read-only inputs, so this may
+/// be called concurrently across modules (see the `into_par_iter` call
+/// site) — callers are responsible for folding the results back into
+/// the environment contexts in a deterministic order afterward.
+fn compute_module_changes(
+  params: ComputeModuleChangesParams,
+) -> Result<ModuleChanges> {
+  let specifier = params.specifier;
+  let module = params.module_graph.get(specifier);
+  let mut polyfills = HashSet::new();
+  let mut used_shim = false;
+  let mut warnings = Vec::new();
+
+  let file_text = match module {
+    ModuleRef::Es(module) => {
+      let parsed_source = module.parsed_source.clone();
+
+      let text_changes = parsed_source
+        .with_view(|program| -> Result<Vec<TextChange>> {
+          let ignore_line_indexes =
+            get_ignore_line_indexes(parsed_source.specifier(), &program);
+          warnings.extend(ignore_line_indexes.warnings);
+
+          fill_polyfills(&mut FillPolyfillsParams {
+            polyfills: &mut polyfills,
+            program: &program,
+            top_level_context: parsed_source.top_level_context(),
+          });
+
+          let mut text_changes = Vec::new();
+
+          // shim changes
+          {
+            let shim_relative_specifier = get_relative_specifier(
+              params.mappings.get_file_path(specifier),
+              params.mappings.get_file_path(params.shim_file_specifier),
+            );
+            let result =
+              get_global_text_changes(&GetGlobalTextChangesParams {
+                program: &program,
+                top_level_context: parsed_source.top_level_context(),
+                shim_specifier: &shim_relative_specifier,
+                shim_global_names: params.shim_global_names,
+                ignore_line_indexes: &ignore_line_indexes.line_indexes,
+              });
+            text_changes.extend(result.text_changes);
+            if result.imported_shim {
+              used_shim = true;
+            }
+          }
+
+          text_changes
+            .extend(get_deno_comment_directive_text_changes(&program));
+          text_changes.extend(get_import_exports_text_changes(
+            &GetImportExportsTextChangesParams {
+              specifier,
+              module_graph: params.module_graph,
+              mappings: params.mappings,
+              program: &program,
+              specifier_mappings: params.all_specifier_mappings,
+            },
+          )?);
+
+          Ok(text_changes)
+        })
+        .with_context(|| {
+          format!(
+            "Issue getting text changes from {}",
+            parsed_source.specifier()
+          )
+        })?;
+
+      Some(apply_text_changes(
+        parsed_source.source().text().to_string(),
+        text_changes,
+      ))
+    }
+    ModuleRef::Synthetic(module) => module.maybe_source.as_ref().map(|source| {
+      format!(
+        "export default JSON.parse(`{}`);",
+        strip_bom(&source.replace("`", "\\`").replace("${", "\\${"))
+      )
+    }),
+  };
+
+  let (file_text, extra_dependencies) = match file_text {
+    Some(file_text) if !params.type_reference_mappings.is_empty() => {
+      let rewrite = rewrite_type_references(
+        &file_text,
+        params.type_reference_mappings,
+      );
+      (Some(rewrite.file_text), rewrite.dependencies)
+    }
+    file_text => (file_text, Vec::new()),
+  };
+
+  Ok(ModuleChanges {
+    specifier: specifier.clone(),
+    is_test: params.is_test,
+    file_text,
+    polyfills,
+    used_shim,
+    warnings,
+    extra_dependencies,
+  })
+}
+
 fn check_add_polyfill_file_to_environment(
   env_context: &mut EnvironmentContext,
   polyfill_file_path: &Path,
@@ -416,6 +556,40 @@ fn get_dependencies(
   dependencies
 }
 
+/// Seeds `specifier_mappings` and `redirects` from an import map's
+/// top-level `imports` entries whose key is itself a literal specifier
+/// (ex. an exact URL). A bare-specifier key (ex. `"preact"`) or a
+/// `/`-suffixed prefix key can only be matched by resolving a
+/// specifier against its referrer while building the module graph,
+/// which this checkout's graph builder doesn't do, so those entries
+/// are skipped rather than pretended to work.
+fn apply_top_level_import_map_entries(
+  import_map: &ImportMap,
+  specifier_mappings: &mut HashMap<ModuleSpecifier, MappedSpecifier>,
+  redirects: &mut HashMap<ModuleSpecifier, ModuleSpecifier>,
+) -> Result<()> {
+  for (key, target) in import_map.top_level_entries() {
+    if key.ends_with('/') {
+      continue;
+    }
+    let from = match ModuleSpecifier::parse(key) {
+      Ok(specifier) => specifier,
+      // not a url (ex. a bare specifier like "preact"), so the entry
+      // is skipped and not applied anywhere else either
+      Err(_) => continue,
+    };
+    match classify_resolved_target(target)? {
+      ResolvedImportMapTarget::Mapped(mapped) => {
+        specifier_mappings.insert(from, mapped);
+      }
+      ResolvedImportMapTarget::Redirect(to) => {
+        redirects.insert(from, to);
+      }
+    }
+  }
+  Ok(())
+}
+
 fn get_declaration_warnings(specifiers: &Specifiers) -> Vec<String> {
   let mut messages = Vec::new();
   for (code_specifier, d) in specifiers.types.iter() {
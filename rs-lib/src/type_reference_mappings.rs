@@ -0,0 +1,106 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashMap;
+
+use crate::MappedSpecifier;
+
+/// The result of rewriting a file's `/// <reference types="..." />` and
+/// `@deno-types="..."` directives against a user-supplied
+/// `TransformOptions::type_reference_mappings` table: the directive lines
+/// are stripped out of the text and each mapped target becomes a
+/// dependency to register on the output environment.
+pub struct TypeReferenceRewrite {
+  pub file_text: String,
+  pub dependencies: Vec<MappedSpecifier>,
+}
+
+/// Strips any `/// <reference types="..." />` or `@deno-types="..."`
+/// directive whose specifier is a key of `type_mappings`, replacing it
+/// with a dependency on the mapped npm package (ex. `@types/node`)
+/// instead of following the ambient Deno typings it points at.
+pub fn rewrite_type_references(
+  file_text: &str,
+  type_mappings: &HashMap<String, MappedSpecifier>,
+) -> TypeReferenceRewrite {
+  let mut dependencies = Vec::new();
+  let mut output = String::with_capacity(file_text.len());
+
+  for line in file_text.split_inclusive('\n') {
+    let specifier_text = extract_reference_types_specifier(line)
+      .or_else(|| extract_deno_types_specifier(line));
+    match specifier_text.and_then(|s| type_mappings.get(s)) {
+      Some(mapped) => dependencies.push(mapped.clone()),
+      None => output.push_str(line),
+    }
+  }
+
+  TypeReferenceRewrite {
+    file_text: output,
+    dependencies,
+  }
+}
+
+fn extract_reference_types_specifier(line: &str) -> Option<&str> {
+  let rest = line.trim().strip_prefix("///")?.trim_start();
+  let rest = rest.strip_prefix("<reference")?.trim_start();
+  let rest = rest.strip_prefix("types")?.trim_start();
+  let rest = rest.strip_prefix('=')?.trim_start();
+  let rest = rest.strip_prefix('"')?;
+  let (specifier, _) = rest.split_once('"')?;
+  Some(specifier)
+}
+
+fn extract_deno_types_specifier(line: &str) -> Option<&str> {
+  let rest = line.trim().strip_prefix("//")?.trim_start();
+  let rest = rest.strip_prefix("@deno-types")?.trim_start();
+  let rest = rest.strip_prefix('=')?.trim_start();
+  let rest = rest.strip_prefix('"')?;
+  let (specifier, _) = rest.split_once('"')?;
+  Some(specifier)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn mappings() -> HashMap<String, MappedSpecifier> {
+    let mut map = HashMap::new();
+    map.insert(
+      "./node.d.ts".to_string(),
+      MappedSpecifier {
+        name: "@types/node".to_string(),
+        version: Some("18.0.0".to_string()),
+      },
+    );
+    map
+  }
+
+  #[test]
+  fn strips_mapped_triple_slash_reference() {
+    let result = rewrite_type_references(
+      "/// <reference types=\"./node.d.ts\" />\nconst a = 1;\n",
+      &mappings(),
+    );
+    assert_eq!(result.file_text, "const a = 1;\n");
+    assert_eq!(result.dependencies.len(), 1);
+    assert_eq!(result.dependencies[0].name, "@types/node");
+  }
+
+  #[test]
+  fn strips_mapped_deno_types_pragma() {
+    let result = rewrite_type_references(
+      "// @deno-types=\"./node.d.ts\"\nimport fs from \"./fs.js\";\n",
+      &mappings(),
+    );
+    assert_eq!(result.file_text, "import fs from \"./fs.js\";\n");
+    assert_eq!(result.dependencies.len(), 1);
+  }
+
+  #[test]
+  fn leaves_unmapped_directives_untouched() {
+    let text = "/// <reference types=\"./other.d.ts\" />\nconst a = 1;\n";
+    let result = rewrite_type_references(text, &mappings());
+    assert_eq!(result.file_text, text);
+    assert!(result.dependencies.is_empty());
+  }
+}